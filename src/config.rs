@@ -0,0 +1,43 @@
+//! Server configuration: the address pool bounds, the network parameters
+//! handed to clients, lease-duration limits and static MAC→IP reservations.
+//! [`ServerConfig::default`] reproduces the values the server used to bake in.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+pub struct ServerConfig {
+    /// Inclusive address range the pool draws from.
+    pub pool_start : Ipv4Addr,
+    pub pool_end : Ipv4Addr,
+    pub subnet_mask : Ipv4Addr,
+    pub gateway : Ipv4Addr,
+    /// This server's own address, advertised as the server identifier (option
+    /// 54). Distinct from `gateway`, since the server need not be the router.
+    pub server_id : Ipv4Addr,
+    pub dns_servers : Vec<Ipv4Addr>,
+    /// Lease term granted when the client does not request one.
+    pub default_lease : Duration,
+    /// Bounds a client's requested lease time is clamped to.
+    pub min_lease : Duration,
+    pub max_lease : Duration,
+    /// Addresses pinned to specific clients by MAC.
+    pub reservations : HashMap<[u8; 6], Ipv4Addr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            pool_start : Ipv4Addr::new(192, 168, 1, 100),
+            pool_end : Ipv4Addr::new(192, 168, 1, 199),
+            subnet_mask : Ipv4Addr::new(255, 255, 255, 0),
+            gateway : Ipv4Addr::new(192, 168, 1, 1),
+            server_id : Ipv4Addr::new(192, 168, 1, 1),
+            dns_servers : vec![Ipv4Addr::new(8, 8, 8, 8)],
+            default_lease : Duration::from_secs(86400),
+            min_lease : Duration::from_secs(300),
+            max_lease : Duration::from_secs(86400),
+            reservations : HashMap::new(),
+        }
+    }
+}
@@ -1,64 +1,187 @@
-use std::{collections::HashMap, net::{Ipv4Addr, UdpSocket}, time::{Duration, SystemTime}};
+mod config;
+mod pool;
+mod stash;
+mod wire;
+
+use std::{collections::HashMap, net::{Ipv4Addr, SocketAddr, UdpSocket}, time::{Duration, SystemTime}};
+
+use config::ServerConfig;
+use pool::AddressPool;
+use stash::{Stash, StoredLease};
+use wire::{DhcpPacket, DhcpReply};
+
+/// Where leases are persisted between restarts.
+const LEASE_STASH_PATH : &str = "leases.json";
+
+/// Reasons a request cannot be satisfied. Distinguishing them lets `run()`
+/// decide between replying with a DHCPNAK and dropping the packet silently,
+/// rather than blindly `continue`-ing.
+#[derive(Debug)]
+enum ServerError {
+    /// The client's option-54 server identifier names a different server;
+    /// this request is not for us (the "UnwantedDHCPServer" case).
+    UnwantedServer,
+    /// We hold no binding for this client.
+    NoLease,
+    /// The client's requested IP (option 50) does not match its binding.
+    RequestedIpMismatch,
+}
 
 #[derive(Debug)]
 struct LeaseEntry {
     mac_address : [u8; 6],
     ip_address : Ipv4Addr,
     lease_expiry : SystemTime,
+    /// Duration actually granted to the client, so renewals and expiry key off
+    /// the per-lease value rather than a single server-wide constant.
+    granted_duration : Duration,
 }
 
 struct DHCPServer {
     socket : UdpSocket,
-    available_pool : Vec<Ipv4Addr>,
+    config : ServerConfig,
+    pool : AddressPool,
     leases : HashMap<[u8; 6], LeaseEntry>,
-    subnet_mask : Ipv4Addr,
-    gateway : Ipv4Addr,
-    dns_servers : Vec<Ipv4Addr>,
-    lease_duration : Duration,
+    /// Optional captive-portal URI advertised via option 114 to clients that
+    /// request it.
+    captive_url : Option<String>,
+    stash : Stash,
 }
 
 impl DHCPServer {
-    fn new() -> std::io::Result<Self> {
+    fn new(config : ServerConfig) -> std::io::Result<Self> {
         let socket = UdpSocket::bind("0.0.0.0:67")?;
         socket.set_broadcast(true)?;
 
-        let mut available_pool = Vec::new();
-        for i in 100..200 {
-            available_pool.push(Ipv4Addr::new(192, 168, 1, i));
+        let mut pool = AddressPool::new(config.pool_start, config.pool_end);
+
+        // Pin reserved addresses out of general circulation.
+        for &ip in config.reservations.values() {
+            pool.reserve(ip);
+        }
+
+        let stash = Stash::new(LEASE_STASH_PATH);
+
+        // Rebuild the lease table from disk, dropping anything already
+        // expired, and marking still-valid addresses allocated so we don't
+        // re-offer an address that is currently leased out.
+        let mut leases = HashMap::new();
+        let now = SystemTime::now();
+        for stored in stash.load()? {
+            if stored.expiry <= now {
+                continue;
+            }
+            pool.allocate(stored.ip);
+            let granted = if stored.granted_secs != 0 {
+                Duration::from_secs(stored.granted_secs)
+            } else {
+                // Pre-upgrade entry without a stored term: fall back to the
+                // remaining time.
+                stored.expiry.duration_since(now).unwrap_or_default()
+            };
+            leases.insert(stored.mac, LeaseEntry {
+                mac_address : stored.mac,
+                ip_address : stored.ip,
+                lease_expiry : stored.expiry,
+                granted_duration : granted,
+            });
         }
 
         Ok(DHCPServer {
             socket,
-            available_pool,
-            leases : HashMap::new(),
-            subnet_mask : Ipv4Addr::new(255, 255, 255, 0),
-            gateway : Ipv4Addr::new(192, 168, 1, 1),
-            dns_servers : vec![Ipv4Addr::new(8, 8, 8, 8)],
-            lease_duration : Duration::from_secs(86400),
+            config,
+            pool,
+            leases,
+            captive_url : None,
+            stash,
         })
     }
 
-    fn process_discover(&mut self, mac_address : [u8; 6]) -> Option<Ipv4Addr>{
+    /// Configure the captive-portal URI advertised via option 114.
+    fn with_captive_url(mut self, url : impl Into<String>) -> Self {
+        self.captive_url = Some(url.into());
+        self
+    }
+
+    /// Write the current lease table through to the stash. Persistence errors
+    /// are logged but not fatal: a lost write costs us at most a duplicate
+    /// offer after a crash, which is preferable to taking the server down.
+    fn persist_leases(&self) {
+        let entries : Vec<StoredLease> = self.leases
+            .values()
+            .map(|lease| StoredLease {
+                mac : lease.mac_address,
+                ip : lease.ip_address,
+                expiry : lease.lease_expiry,
+                granted_secs : lease.granted_duration.as_secs(),
+            })
+            .collect();
+        if let Err(e) = self.stash.store(&entries) {
+            eprintln!("failed to persist leases: {e}");
+        }
+    }
+
+    fn process_discover(&mut self, packet : &DhcpPacket) -> Option<Ipv4Addr>{
+        let mac_address = packet.chaddr;
+
         // Clean expired leases
         self.clean_expired_leases();
 
-        // Check if client already has a lease
-        if let Some(lease) = self.leases.get(&mac_address) {
-            return Some(lease.ip_address);
+        let granted = self.grant_duration(packet.requested_lease_time);
+
+        // Check if client already has a lease; refresh its term in case the
+        // client asked for a different duration this time around.
+        if let Some(lease) = self.leases.get_mut(&mac_address) {
+            lease.granted_duration = granted;
+            lease.lease_expiry = SystemTime::now() + granted;
+            let ip = lease.ip_address;
+            self.persist_leases();
+            return Some(ip);
         }
 
-        // Find available IP
-        if let Some(ip) = self.available_pool.pop() {
-            let lease = LeaseEntry {
-                mac_address,
-                ip_address : ip,
-                lease_expiry : SystemTime::now() + self.lease_duration,
-            };
+        // Allocation priority, matching typical server behavior: honor the
+        // client's requested IP (option 50) if it is a free pool address, then
+        // any static reservation for this MAC, and only then the next free
+        // address.
+        let ip = self.select_address(&mac_address, packet.requested_ip)?;
 
-            self.leases.insert(mac_address, lease);
-            Some(ip)
-        } else {
-            None
+        let lease = LeaseEntry {
+            mac_address,
+            ip_address : ip,
+            lease_expiry : SystemTime::now() + granted,
+            granted_duration : granted,
+        };
+
+        self.leases.insert(mac_address, lease);
+        self.persist_leases();
+        Some(ip)
+    }
+
+    /// Choose and allocate an address for a new binding, following the
+    /// requested-IP → reservation → next-free priority.
+    fn select_address(&mut self, mac : &[u8; 6], requested : Option<Ipv4Addr>) -> Option<Ipv4Addr> {
+        if let Some(ip) = requested {
+            if self.pool.is_available(ip) && self.pool.allocate(ip) {
+                return Some(ip);
+            }
+        }
+
+        if let Some(&ip) = self.config.reservations.get(mac) {
+            if self.pool.allocate(ip) {
+                return Some(ip);
+            }
+        }
+
+        self.pool.take_any()
+    }
+
+    /// Clamp a client's requested lease time (option 51) to the configured
+    /// bounds, defaulting to the server's lease duration when none is asked.
+    fn grant_duration(&self, requested : Option<u32>) -> Duration {
+        match requested {
+            Some(secs) => Duration::from_secs(secs as u64)
+                .clamp(self.config.min_lease, self.config.max_lease),
+            None => self.config.default_lease,
         }
     }
 
@@ -70,10 +193,15 @@ impl DHCPServer {
             .map(|(mac, lease)| (*mac, lease.ip_address))
             .collect();
 
+        if expired.is_empty() {
+            return;
+        }
+
         for (mac, ip) in expired {
             self.leases.remove(&mac);
-            self.available_pool.push(ip);
+            self.pool.release(ip);
         }
+        self.persist_leases();
     }
 
     fn run(&mut self) -> std::io::Result<()> {
@@ -81,55 +209,210 @@ impl DHCPServer {
 
         loop {
             let (size, src) = self.socket.recv_from(&mut buffer)?;
-            if size < 241 {
-                continue;
-            }
 
-            let message_type = match self.get_dhcp_message_type(&buffer[..size]) {
-                Some(t) => t,
+            let packet = match DhcpPacket::parse(&buffer[..size]) {
+                Some(p) => p,
                 None => continue,
             };
 
-            let mac_address = self.get_mac_address(&buffer);
-
-            match message_type {
-                1 => {
-                    if let Some(offer_ip) = self.process_discover(mac_address) {
-                        self.send_offer(src, mac_address, offer_ip)?;
+            match packet.message_type {
+                Some(wire::DHCPDISCOVER) => {
+                    if let Some(offer_ip) = self.process_discover(&packet) {
+                        self.send_offer(src, &packet, offer_ip)?;
                     }
                 }
-                3 => {
-                    self.send_ack(src, mac_address)?;
+                Some(wire::DHCPREQUEST) => {
+                    match self.process_request(&packet) {
+                        Ok(ip) => self.send_ack(src, &packet, ip)?,
+                        // The client is committing to the wrong address: tell
+                        // it to start over with a NAK.
+                        Err(ServerError::RequestedIpMismatch) => self.send_nak(src, &packet)?,
+                        // No binding of ours, or another server was selected:
+                        // stay silent so we don't disturb a lease we don't own.
+                        Err(ServerError::NoLease)
+                        | Err(ServerError::UnwantedServer) => {}
+                    }
                 }
+                Some(wire::DHCPDECLINE) => self.process_decline(&packet),
+                Some(wire::DHCPRELEASE) => self.process_release(&packet),
+                Some(wire::DHCPINFORM) => self.send_inform_ack(src, &packet)?,
                 _ => continue,
             }
         }
     }
 
-    fn get_dhcp_message_type(&self, packet: &[u8]) -> Option<u8> {
-        Some(packet[0])
+    /// Validate and satisfy a DHCPREQUEST, returning the address to ACK.
+    fn process_request(&mut self, packet : &DhcpPacket) -> Result<Ipv4Addr, ServerError> {
+        // If the client selected a server (option 54), it must be us.
+        if let Some(server_id) = packet.server_id {
+            if server_id != self.config.server_id {
+                return Err(ServerError::UnwantedServer);
+            }
+        }
+
+        // A client commonly carries its desired lease time (option 51) on the
+        // REQUEST rather than the DISCOVER, so honor it here too.
+        let requested_grant = packet.requested_lease_time.map(|secs| self.grant_duration(Some(secs)));
+
+        let lease = self.leases.get_mut(&packet.chaddr).ok_or(ServerError::NoLease)?;
+        let lease_ip = lease.ip_address;
+
+        // The address the client is committing to must be the one we offered.
+        if let Some(requested) = packet.requested_ip {
+            if requested != lease_ip {
+                return Err(ServerError::RequestedIpMismatch);
+            }
+        }
+
+        // A REQUEST confirms (or renews) the binding, so extend the expiry by
+        // the term we granted this lease.
+        if let Some(granted) = requested_grant {
+            lease.granted_duration = granted;
+        }
+        lease.lease_expiry = SystemTime::now() + lease.granted_duration;
+        self.persist_leases();
+        Ok(lease_ip)
+    }
+
+    /// DHCPDECLINE: the client detected the address is already in use. Mark it
+    /// conflicted so we never hand it out again, and drop the binding.
+    fn process_decline(&mut self, packet : &DhcpPacket) {
+        // Only act on the address we actually leased to this client, so a
+        // stray option 50 can't mark an unrelated address as conflicted.
+        let ip = match self.leases.get(&packet.chaddr) {
+            Some(lease) => lease.ip_address,
+            None => return,
+        };
+        if let Some(requested) = packet.requested_ip {
+            if requested != ip {
+                return;
+            }
+        }
+        self.pool.mark_conflicted(ip);
+        self.leases.remove(&packet.chaddr);
+        self.persist_leases();
+    }
+
+    /// DHCPRELEASE: the client is done with its address; return it to the pool.
+    fn process_release(&mut self, packet : &DhcpPacket) {
+        let ip = match self.leases.get(&packet.chaddr) {
+            Some(lease) => lease.ip_address,
+            None => return,
+        };
+        // Ignore a release that names an address other than the client's own.
+        if let Some(requested) = packet.requested_ip {
+            if requested != ip {
+                return;
+            }
+        }
+        self.leases.remove(&packet.chaddr);
+        self.pool.release(ip);
+        self.persist_leases();
+    }
+
+    /// Pick the destination for a reply. We must broadcast when the client set
+    /// the broadcast flag, and also when it has no configured address yet — in
+    /// the SELECTING/INIT states `src` is `0.0.0.0:68`, which is undeliverable,
+    /// so a unicast reply there would be lost. Only a client that already holds
+    /// an address (a unicast RENEWING request from its own IP) gets a unicast
+    /// reply.
+    fn reply_dest(&self, src : SocketAddr, packet : &DhcpPacket) -> SocketAddr {
+        if packet.wants_broadcast() || src.ip().is_unspecified() {
+            SocketAddr::from((Ipv4Addr::BROADCAST, 68))
+        } else {
+            src
+        }
     }
 
-    fn get_mac_address(&self, packet: &[u8]) -> [u8; 6] {
-        let mut mac = [0u8; 6];
-        mac.copy_from_slice(&packet[28..34]);
-        mac
+    fn build_reply(&self, message_type : u8, packet : &DhcpPacket, ip : Ipv4Addr) -> DhcpReply {
+        // Advertise the term we actually granted this client, plus the T1/T2
+        // renewal timers derived from it (RFC 2131 §4.4.5 defaults).
+        let lease_secs = self.leases
+            .get(&packet.chaddr)
+            .map(|lease| lease.granted_duration.as_secs())
+            .unwrap_or_else(|| self.config.default_lease.as_secs()) as u32;
+
+        DhcpReply {
+            message_type,
+            xid : packet.xid,
+            flags : packet.flags,
+            chaddr : packet.chaddr,
+            yiaddr : ip,
+            server_id : self.config.server_id,
+            subnet_mask : self.config.subnet_mask,
+            routers : vec![self.config.gateway],
+            dns_servers : self.config.dns_servers.clone(),
+            lease_time : lease_secs,
+            renewal_t1 : lease_secs / 2,
+            rebinding_t2 : (lease_secs / 8) * 7,
+            captive_url : self.captive_url_for(packet),
+        }
     }
 
-    fn send_offer(&self, dest : std::net::SocketAddr, mac : [u8; 6], ip : Ipv4Addr) -> std::io::Result<()> {
-        // Simplified DHCP offer packet construction
-        let mut response = vec![0u8; 300];
+    /// Advertise the captive-portal URI only when one is configured and the
+    /// client asked for option 114, so we don't bloat every reply.
+    fn captive_url_for(&self, packet : &DhcpPacket) -> Option<String> {
+        if packet.param_request_list.contains(&wire::OPT_CAPTIVE_PORTAL) {
+            self.captive_url.clone()
+        } else {
+            None
+        }
+    }
 
-        //...Fill in DHCP offer packet fields...
-        self.socket.send_to(&response, dest)?;
+    fn send_offer(&self, src : SocketAddr, packet : &DhcpPacket, ip : Ipv4Addr) -> std::io::Result<()> {
+        let response = self.build_reply(wire::DHCPOFFER, packet, ip).serialize();
+        self.socket.send_to(&response, self.reply_dest(src, packet))?;
         Ok(())
     }
 
-    fn send_ack(&self, dest : std::net::SocketAddr, mac : [u8; 6]) -> std::io::Result<()>{
-        // Simplified DHCP ACK packet construction
-        let mut response = vec![0u8; 300];
-        // ...Fill in DHCP ACK packet fields...
-        self.socket.send_to(&response, dest)?;
+    fn send_ack(&self, src : SocketAddr, packet : &DhcpPacket, ip : Ipv4Addr) -> std::io::Result<()> {
+        let response = self.build_reply(wire::DHCPACK, packet, ip).serialize();
+        self.socket.send_to(&response, self.reply_dest(src, packet))?;
+        Ok(())
+    }
+
+    /// DHCPNAK: the binding the client asked for is not valid. A NAK carries
+    /// no address configuration and is always broadcast.
+    fn send_nak(&self, _src : SocketAddr, packet : &DhcpPacket) -> std::io::Result<()> {
+        let reply = DhcpReply {
+            message_type : wire::DHCPNAK,
+            xid : packet.xid,
+            flags : packet.flags,
+            chaddr : packet.chaddr,
+            yiaddr : Ipv4Addr::UNSPECIFIED,
+            server_id : self.config.server_id,
+            subnet_mask : Ipv4Addr::UNSPECIFIED,
+            routers : Vec::new(),
+            dns_servers : Vec::new(),
+            lease_time : 0,
+            renewal_t1 : 0,
+            rebinding_t2 : 0,
+            captive_url : None,
+        };
+        let dest = SocketAddr::from((Ipv4Addr::BROADCAST, 68));
+        self.socket.send_to(&reply.serialize(), dest)?;
+        Ok(())
+    }
+
+    /// DHCPINFORM: the client already has an address and only wants
+    /// configuration. Reply with an ACK carrying options but no lease.
+    fn send_inform_ack(&self, src : SocketAddr, packet : &DhcpPacket) -> std::io::Result<()> {
+        let reply = DhcpReply {
+            message_type : wire::DHCPACK,
+            xid : packet.xid,
+            flags : packet.flags,
+            chaddr : packet.chaddr,
+            yiaddr : Ipv4Addr::UNSPECIFIED,
+            server_id : self.config.server_id,
+            subnet_mask : self.config.subnet_mask,
+            routers : vec![self.config.gateway],
+            dns_servers : self.config.dns_servers.clone(),
+            lease_time : 0,
+            renewal_t1 : 0,
+            rebinding_t2 : 0,
+            captive_url : self.captive_url_for(packet),
+        };
+        self.socket.send_to(&reply.serialize(), self.reply_dest(src, packet))?;
         Ok(())
     }
 }
@@ -137,6 +420,11 @@ impl DHCPServer {
 
 
 fn main() -> std::io::Result<()> {
-    let mut server = DHCPServer::new()?;
+    let mut server = DHCPServer::new(ServerConfig::default())?;
+    if let Ok(url) = std::env::var("DHCP_CAPTIVE_URL") {
+        if !url.trim().is_empty() {
+            server = server.with_captive_url(url);
+        }
+    }
     server.run()
 }
@@ -0,0 +1,173 @@
+//! Address allocation state. Unlike a plain `Vec` of free addresses, the pool
+//! tracks each address as available, allocated, or conflicted so that declined
+//! addresses stay out of rotation and a specific address can be requested by
+//! the client. Reserved addresses are pinned to their client and kept out of
+//! general circulation.
+//!
+//! Sets are ordered (`BTreeSet`) so allocation is deterministic across runs.
+
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+
+/// The set of addresses the server manages, partitioned by state.
+pub struct AddressPool {
+    /// Free addresses in general circulation.
+    available : BTreeSet<Ipv4Addr>,
+    /// Addresses pinned to a client; never handed out by `take_any`, but their
+    /// owner can always `allocate` them. Membership is permanent.
+    reserved : BTreeSet<Ipv4Addr>,
+    allocated : BTreeSet<Ipv4Addr>,
+    conflicted : BTreeSet<Ipv4Addr>,
+}
+
+impl AddressPool {
+    /// Build a pool spanning `start..=end` inclusive, all initially available.
+    pub fn new(start : Ipv4Addr, end : Ipv4Addr) -> Self {
+        let mut available = BTreeSet::new();
+        for raw in u32::from(start)..=u32::from(end) {
+            available.insert(Ipv4Addr::from(raw));
+        }
+        AddressPool {
+            available,
+            reserved : BTreeSet::new(),
+            allocated : BTreeSet::new(),
+            conflicted : BTreeSet::new(),
+        }
+    }
+
+    /// Pin an address to a client: removed from general circulation so
+    /// `take_any` never hands it to anyone else, while its owner can still
+    /// [`allocate`](Self::allocate) it. Works for addresses outside the
+    /// configured range too.
+    pub fn reserve(&mut self, ip : Ipv4Addr) {
+        self.available.remove(&ip);
+        self.reserved.insert(ip);
+    }
+
+    /// Whether `ip` is free for a client to claim by explicit request, i.e. in
+    /// general circulation and not reserved for another client.
+    pub fn is_available(&self, ip : Ipv4Addr) -> bool {
+        self.available.contains(&ip)
+    }
+
+    /// Move a specific address into the allocated set, returning whether it was
+    /// free to take. Accepts both generally-available and reserved addresses.
+    pub fn allocate(&mut self, ip : Ipv4Addr) -> bool {
+        if self.conflicted.contains(&ip) || self.allocated.contains(&ip) {
+            return false;
+        }
+        if self.available.remove(&ip) || self.reserved.contains(&ip) {
+            self.allocated.insert(ip);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Allocate an arbitrary free address from general circulation, if any
+    /// remain. Reserved addresses are never returned here.
+    pub fn take_any(&mut self) -> Option<Ipv4Addr> {
+        let ip = *self.available.iter().next()?;
+        self.available.remove(&ip);
+        self.allocated.insert(ip);
+        Some(ip)
+    }
+
+    /// Return an allocated address to circulation. A reserved address stays
+    /// pinned (available only to its owner); a declined one stays conflicted.
+    pub fn release(&mut self, ip : Ipv4Addr) {
+        if !self.allocated.remove(&ip) || self.conflicted.contains(&ip) {
+            return;
+        }
+        if !self.reserved.contains(&ip) {
+            self.available.insert(ip);
+        }
+    }
+
+    /// Mark an address as conflicted (e.g. after a DHCPDECLINE) so it is never
+    /// offered again.
+    pub fn mark_conflicted(&mut self, ip : Ipv4Addr) {
+        self.available.remove(&ip);
+        self.allocated.remove(&ip);
+        self.conflicted.insert(ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last : u8) -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 1, last)
+    }
+
+    fn pool() -> AddressPool {
+        AddressPool::new(ip(100), ip(102))
+    }
+
+    #[test]
+    fn take_any_is_deterministic_and_ascending() {
+        let mut pool = pool();
+        assert_eq!(pool.take_any(), Some(ip(100)));
+        assert_eq!(pool.take_any(), Some(ip(101)));
+        assert_eq!(pool.take_any(), Some(ip(102)));
+        assert_eq!(pool.take_any(), None);
+    }
+
+    #[test]
+    fn honors_an_available_requested_address() {
+        let mut pool = pool();
+        assert!(pool.is_available(ip(101)));
+        assert!(pool.allocate(ip(101)));
+        // No longer free, and take_any skips it.
+        assert!(!pool.is_available(ip(101)));
+        assert_eq!(pool.take_any(), Some(ip(100)));
+    }
+
+    #[test]
+    fn reservation_is_pinned_to_its_owner() {
+        let mut pool = pool();
+        pool.reserve(ip(101));
+        // A general request never hands out the reservation...
+        assert!(!pool.is_available(ip(101)));
+        assert_eq!(pool.take_any(), Some(ip(100)));
+        assert_eq!(pool.take_any(), Some(ip(102)));
+        assert_eq!(pool.take_any(), None);
+        // ...but the owner can still allocate it explicitly.
+        assert!(pool.allocate(ip(101)));
+    }
+
+    #[test]
+    fn reservation_works_outside_the_pool_range() {
+        let mut pool = pool();
+        pool.reserve(ip(50));
+        assert!(pool.allocate(ip(50)));
+    }
+
+    #[test]
+    fn release_returns_address_but_decline_keeps_it_out() {
+        let mut pool = pool();
+        assert!(pool.allocate(ip(100)));
+        pool.release(ip(100));
+        assert!(pool.is_available(ip(100)));
+
+        assert!(pool.allocate(ip(100)));
+        pool.mark_conflicted(ip(100));
+        assert!(!pool.is_available(ip(100)));
+        // A later release of a conflicted address must not revive it.
+        pool.release(ip(100));
+        assert!(!pool.is_available(ip(100)));
+        assert!(!pool.allocate(ip(100)));
+    }
+
+    #[test]
+    fn released_reservation_stays_reserved() {
+        let mut pool = pool();
+        pool.reserve(ip(101));
+        assert!(pool.allocate(ip(101)));
+        pool.release(ip(101));
+        // Back to reserved, not general circulation.
+        assert!(!pool.is_available(ip(101)));
+        assert!(pool.allocate(ip(101)));
+    }
+}
@@ -0,0 +1,173 @@
+//! Lease persistence, modelled on Fuchsia's DHCP "Stash": a small key-value
+//! store, keyed by client MAC, that the server writes through on every lease
+//! change and reloads on startup so addresses that are still leased out are
+//! not re-offered after a restart.
+//!
+//! Entries are stored as line-delimited JSON (one object per line) using only
+//! the standard library; each line is
+//! `{"mac":..,"ip":..,"expiry":..,"granted":..}` where `expiry` is the absolute
+//! lease end in seconds since the Unix epoch and `granted` is the lease term in
+//! seconds.
+
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A lease as it lives on disk: client MAC, assigned address and the absolute
+/// expiry time.
+pub struct StoredLease {
+    pub mac : [u8; 6],
+    pub ip : Ipv4Addr,
+    pub expiry : SystemTime,
+    /// Duration granted to the client, in seconds, so renewals after a restart
+    /// use the same term rather than the remaining time.
+    pub granted_secs : u64,
+}
+
+/// The backing store. Cheap to clone-free share by reference; all writes
+/// rewrite the whole file, which is adequate for the pool sizes we serve.
+pub struct Stash {
+    path : PathBuf,
+}
+
+impl Stash {
+    pub fn new(path : impl AsRef<Path>) -> Self {
+        Stash { path : path.as_ref().to_path_buf() }
+    }
+
+    /// Load every entry currently on disk. A missing file is treated as an
+    /// empty store. Malformed lines are skipped rather than aborting startup.
+    pub fn load(&self) -> io::Result<Vec<StoredLease>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(entry) = parse_line(line) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Persist the full set of entries, replacing the file's contents.
+    pub fn store(&self, entries : &[StoredLease]) -> io::Result<()> {
+        let mut out = String::new();
+        for entry in entries {
+            out.push_str(&serialize_line(entry));
+            out.push('\n');
+        }
+        fs::write(&self.path, out)
+    }
+}
+
+/// Render one entry as a JSON object.
+fn serialize_line(entry : &StoredLease) -> String {
+    format!(
+        "{{\"mac\":\"{}\",\"ip\":\"{}\",\"expiry\":{},\"granted\":{}}}",
+        encode_mac(&entry.mac),
+        entry.ip,
+        to_epoch_secs(entry.expiry),
+        entry.granted_secs,
+    )
+}
+
+/// Parse one JSON object line into a [`StoredLease`], returning `None` if any
+/// required field is missing or malformed.
+fn parse_line(line : &str) -> Option<StoredLease> {
+    let mac = decode_mac(field(line, "mac")?.trim_matches('"'))?;
+    let ip = field(line, "ip")?.trim_matches('"').parse().ok()?;
+    let expiry_secs : u64 = field(line, "expiry")?.trim().parse().ok()?;
+    // `granted` was added later; default to 0 if absent, which the loader
+    // treats as "use the remaining time".
+    let granted_secs : u64 = field(line, "granted")
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    Some(StoredLease {
+        mac,
+        ip,
+        expiry : from_epoch_secs(expiry_secs),
+        granted_secs,
+    })
+}
+
+/// Extract the raw (still-quoted for strings) value for `key` from a flat JSON
+/// object line. Adequate for the fixed schema we write ourselves.
+fn field<'a>(line : &'a str, key : &str) -> Option<&'a str> {
+    let needle = format!("\"{key}\":");
+    let start = line.find(&needle)? + needle.len();
+    let rest = &line[start..];
+    let end = rest.find([',', '}']).unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
+
+fn encode_mac(mac : &[u8; 6]) -> String {
+    mac.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_mac(s : &str) -> Option<[u8; 6]> {
+    if s.len() != 12 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (i, byte) in mac.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(mac)
+}
+
+fn to_epoch_secs(time : SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn from_epoch_secs(secs : u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_parse_round_trip() {
+        let entry = StoredLease {
+            mac : [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff],
+            ip : Ipv4Addr::new(192, 168, 1, 150),
+            expiry : from_epoch_secs(1_700_000_000),
+            granted_secs : 3600,
+        };
+        let line = serialize_line(&entry);
+        let parsed = parse_line(&line).expect("round-trips");
+
+        assert_eq!(parsed.mac, entry.mac);
+        assert_eq!(parsed.ip, entry.ip);
+        assert_eq!(to_epoch_secs(parsed.expiry), 1_700_000_000);
+        assert_eq!(parsed.granted_secs, 3600);
+    }
+
+    #[test]
+    fn parses_legacy_line_without_granted() {
+        // Entries written before option 51 support lacked the `granted` field.
+        let line = "{\"mac\":\"001122334455\",\"ip\":\"10.0.0.5\",\"expiry\":42}";
+        let parsed = parse_line(line).expect("legacy line still parses");
+
+        assert_eq!(parsed.mac, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        assert_eq!(parsed.ip, Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(to_epoch_secs(parsed.expiry), 42);
+        assert_eq!(parsed.granted_secs, 0);
+    }
+
+    #[test]
+    fn skips_malformed_line() {
+        assert!(parse_line("not json").is_none());
+    }
+}
@@ -0,0 +1,328 @@
+//! RFC 2131 / RFC 2132 wire format: the BOOTP fixed header, the magic
+//! cookie and the TLV options area.
+//!
+//! Incoming datagrams are decoded into a [`DhcpPacket`]; replies are built up
+//! as a [`DhcpReply`] and serialized back onto the wire.
+
+use std::net::Ipv4Addr;
+
+/// DHCP magic cookie that precedes the options area (RFC 2132 §2).
+pub const MAGIC_COOKIE : [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+
+/// Offset of the first options byte: 236-byte BOOTP header + 4-byte cookie.
+const OPTIONS_OFFSET : usize = 240;
+
+/// `op` value for a server reply (BOOTREPLY).
+pub const BOOTREPLY : u8 = 2;
+
+/// Broadcast bit in the BOOTP `flags` field.
+pub const BROADCAST_FLAG : u16 = 0x8000;
+
+// DHCP message types (option 53 values, RFC 2131 §3).
+pub const DHCPDISCOVER : u8 = 1;
+pub const DHCPOFFER : u8 = 2;
+pub const DHCPREQUEST : u8 = 3;
+pub const DHCPDECLINE : u8 = 4;
+pub const DHCPACK : u8 = 5;
+pub const DHCPNAK : u8 = 6;
+pub const DHCPRELEASE : u8 = 7;
+pub const DHCPINFORM : u8 = 8;
+
+// Option codes we care about (RFC 2132).
+pub const OPT_SUBNET_MASK : u8 = 1;
+pub const OPT_ROUTER : u8 = 3;
+pub const OPT_DNS : u8 = 6;
+pub const OPT_REQUESTED_IP : u8 = 50;
+pub const OPT_LEASE_TIME : u8 = 51;
+pub const OPT_MESSAGE_TYPE : u8 = 53;
+pub const OPT_SERVER_ID : u8 = 54;
+pub const OPT_PARAM_REQUEST_LIST : u8 = 55;
+pub const OPT_RENEWAL_T1 : u8 = 58;
+pub const OPT_REBINDING_T2 : u8 = 59;
+pub const OPT_CAPTIVE_PORTAL : u8 = 114;
+pub const OPT_PAD : u8 = 0;
+pub const OPT_END : u8 = 255;
+
+/// A decoded client packet: the BOOTP fields we act on plus the options the
+/// state machine needs.
+#[derive(Debug)]
+pub struct DhcpPacket {
+    pub xid : u32,
+    pub flags : u16,
+    pub chaddr : [u8; 6],
+    pub requested_ip : Option<Ipv4Addr>,
+    pub server_id : Option<Ipv4Addr>,
+    pub requested_lease_time : Option<u32>,
+    pub param_request_list : Vec<u8>,
+    pub message_type : Option<u8>,
+}
+
+impl DhcpPacket {
+    /// Parse a raw datagram. Returns `None` if it is too short to hold the
+    /// fixed header and the magic cookie, or if the cookie is missing.
+    pub fn parse(packet : &[u8]) -> Option<Self> {
+        if packet.len() < OPTIONS_OFFSET {
+            return None;
+        }
+        if packet[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+
+        let xid = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        let flags = u16::from_be_bytes([packet[10], packet[11]]);
+
+        let mut chaddr = [0u8; 6];
+        chaddr.copy_from_slice(&packet[28..34]);
+
+        let mut requested_ip = None;
+        let mut server_id = None;
+        let mut requested_lease_time = None;
+        let mut param_request_list = Vec::new();
+        let mut message_type = None;
+
+        let mut i = OPTIONS_OFFSET;
+        while i < packet.len() {
+            let code = packet[i];
+            if code == OPT_END {
+                break;
+            }
+            if code == OPT_PAD {
+                i += 1;
+                continue;
+            }
+            // Every other option is TLV: code, length, value.
+            if i + 1 >= packet.len() {
+                break;
+            }
+            let len = packet[i + 1] as usize;
+            let value_start = i + 2;
+            let value_end = value_start + len;
+            if value_end > packet.len() {
+                break;
+            }
+            let value = &packet[value_start..value_end];
+
+            match code {
+                OPT_MESSAGE_TYPE if len >= 1 => message_type = Some(value[0]),
+                OPT_REQUESTED_IP if len == 4 => {
+                    requested_ip = Some(Ipv4Addr::new(value[0], value[1], value[2], value[3]));
+                }
+                OPT_SERVER_ID if len == 4 => {
+                    server_id = Some(Ipv4Addr::new(value[0], value[1], value[2], value[3]));
+                }
+                OPT_LEASE_TIME if len == 4 => {
+                    requested_lease_time =
+                        Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+                }
+                OPT_PARAM_REQUEST_LIST => param_request_list = value.to_vec(),
+                _ => {}
+            }
+
+            i = value_end;
+        }
+
+        Some(DhcpPacket {
+            xid,
+            flags,
+            chaddr,
+            requested_ip,
+            server_id,
+            requested_lease_time,
+            param_request_list,
+            message_type,
+        })
+    }
+
+    /// Whether the client set the broadcast bit, meaning it cannot accept a
+    /// unicast reply before its IP stack is configured.
+    pub fn wants_broadcast(&self) -> bool {
+        self.flags & BROADCAST_FLAG != 0
+    }
+}
+
+/// A reply (OFFER / ACK / NAK) to be serialized into a BOOTREPLY datagram.
+pub struct DhcpReply {
+    pub message_type : u8,
+    pub xid : u32,
+    pub flags : u16,
+    pub chaddr : [u8; 6],
+    pub yiaddr : Ipv4Addr,
+    pub server_id : Ipv4Addr,
+    pub subnet_mask : Ipv4Addr,
+    pub routers : Vec<Ipv4Addr>,
+    pub dns_servers : Vec<Ipv4Addr>,
+    pub lease_time : u32,
+    /// Renewal (T1) and rebinding (T2) timers, omitted when zero.
+    pub renewal_t1 : u32,
+    pub rebinding_t2 : u32,
+    /// Captive-portal URI (option 114, RFC 7710), omitted when `None`.
+    pub captive_url : Option<String>,
+}
+
+impl DhcpReply {
+    /// Serialize into the full BOOTP frame: fixed header, magic cookie and
+    /// the TLV options the client needs to configure its interface.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = vec![0u8; OPTIONS_OFFSET];
+
+        out[0] = BOOTREPLY;
+        out[1] = 1; // htype: Ethernet
+        out[2] = 6; // hlen: MAC length
+        out[3] = 0; // hops
+        out[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        // secs (8..10) left zero.
+        out[10..12].copy_from_slice(&self.flags.to_be_bytes());
+        // ciaddr (12..16) left zero.
+        out[16..20].copy_from_slice(&self.yiaddr.octets());
+        // siaddr / giaddr left zero.
+        out[28..34].copy_from_slice(&self.chaddr);
+        // sname / file left zero.
+        out[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        push_option(&mut out, OPT_MESSAGE_TYPE, &[self.message_type]);
+        push_option(&mut out, OPT_SERVER_ID, &self.server_id.octets());
+        // Options left unset (`0.0.0.0` / empty / `0`) are simply omitted
+        // here: a DHCPNAK leaves all the network options unset, and a
+        // DHCPINFORM reply leaves only the lease time and T1/T2 unset.
+        if self.subnet_mask != Ipv4Addr::UNSPECIFIED {
+            push_option(&mut out, OPT_SUBNET_MASK, &self.subnet_mask.octets());
+        }
+        push_addr_list(&mut out, OPT_ROUTER, &self.routers);
+        push_addr_list(&mut out, OPT_DNS, &self.dns_servers);
+        if self.lease_time != 0 {
+            push_option(&mut out, OPT_LEASE_TIME, &self.lease_time.to_be_bytes());
+        }
+        if self.renewal_t1 != 0 {
+            push_option(&mut out, OPT_RENEWAL_T1, &self.renewal_t1.to_be_bytes());
+        }
+        if self.rebinding_t2 != 0 {
+            push_option(&mut out, OPT_REBINDING_T2, &self.rebinding_t2.to_be_bytes());
+        }
+        if let Some(url) = &self.captive_url {
+            // A single option's value is length-prefixed by one byte, so skip
+            // a URI that would not fit rather than emit a truncated option.
+            if (1..=255).contains(&url.len()) {
+                push_option(&mut out, OPT_CAPTIVE_PORTAL, url.as_bytes());
+            }
+        }
+
+        out.push(OPT_END);
+        // Pad to the historical 300-byte BOOTP minimum so relays and strict
+        // clients that enforce it don't drop the reply.
+        if out.len() < 300 {
+            out.resize(300, OPT_PAD);
+        }
+        out
+    }
+}
+
+/// Append a single TLV option.
+fn push_option(out : &mut Vec<u8>, code : u8, value : &[u8]) {
+    out.push(code);
+    out.push(value.len() as u8);
+    out.extend_from_slice(value);
+}
+
+/// Append an option whose value is a concatenation of IPv4 addresses, e.g.
+/// the router (3) or DNS (6) lists. Omitted entirely when the list is empty.
+fn push_addr_list(out : &mut Vec<u8>, code : u8, addrs : &[Ipv4Addr]) {
+    if addrs.is_empty() {
+        return;
+    }
+    let mut value = Vec::with_capacity(addrs.len() * 4);
+    for addr in addrs {
+        value.extend_from_slice(&addr.octets());
+    }
+    push_option(out, code, &value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A well-formed DHCPDISCOVER carrying the options we parse.
+    fn sample_request() -> Vec<u8> {
+        let mut frame = vec![0u8; OPTIONS_OFFSET];
+        frame[0] = 1; // op: BOOTREQUEST
+        frame[1] = 1; // htype: Ethernet
+        frame[2] = 6; // hlen
+        frame[4..8].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        frame[10..12].copy_from_slice(&BROADCAST_FLAG.to_be_bytes());
+        frame[28..34].copy_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        frame[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        push_option(&mut frame, OPT_MESSAGE_TYPE, &[DHCPDISCOVER]);
+        push_option(&mut frame, OPT_REQUESTED_IP, &Ipv4Addr::new(192, 168, 1, 150).octets());
+        push_option(&mut frame, OPT_SERVER_ID, &Ipv4Addr::new(192, 168, 1, 1).octets());
+        push_option(&mut frame, OPT_PARAM_REQUEST_LIST, &[1, 3, 6, 114]);
+        push_option(&mut frame, OPT_LEASE_TIME, &3600u32.to_be_bytes());
+        frame.push(OPT_END);
+        frame
+    }
+
+    #[test]
+    fn parses_known_good_frame() {
+        let packet = DhcpPacket::parse(&sample_request()).expect("should parse");
+        assert_eq!(packet.xid, 0x1234_5678);
+        assert_eq!(packet.chaddr, [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        assert!(packet.wants_broadcast());
+        assert_eq!(packet.message_type, Some(DHCPDISCOVER));
+        assert_eq!(packet.requested_ip, Some(Ipv4Addr::new(192, 168, 1, 150)));
+        assert_eq!(packet.server_id, Some(Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(packet.requested_lease_time, Some(3600));
+        assert_eq!(packet.param_request_list, vec![1, 3, 6, 114]);
+    }
+
+    #[test]
+    fn rejects_missing_magic_cookie() {
+        let mut frame = sample_request();
+        frame[236] = 0;
+        assert!(DhcpPacket::parse(&frame).is_none());
+    }
+
+    #[test]
+    fn rejects_frame_shorter_than_header() {
+        let frame = vec![0u8; OPTIONS_OFFSET - 1];
+        assert!(DhcpPacket::parse(&frame).is_none());
+    }
+
+    #[test]
+    fn ignores_option_running_past_end() {
+        // Option 50 claims four bytes but only two are present before the
+        // buffer ends: parsing must stop cleanly, not panic or read OOB.
+        let mut frame = vec![0u8; OPTIONS_OFFSET];
+        frame[236..240].copy_from_slice(&MAGIC_COOKIE);
+        frame.push(OPT_REQUESTED_IP);
+        frame.push(4);
+        frame.extend_from_slice(&[192, 168]);
+
+        let packet = DhcpPacket::parse(&frame).expect("header still parses");
+        assert_eq!(packet.requested_ip, None);
+    }
+
+    #[test]
+    fn serialize_pads_to_minimum_and_round_trips() {
+        let reply = DhcpReply {
+            message_type : DHCPOFFER,
+            xid : 0x0a0b_0c0d,
+            flags : 0,
+            chaddr : [1, 2, 3, 4, 5, 6],
+            yiaddr : Ipv4Addr::new(192, 168, 1, 120),
+            server_id : Ipv4Addr::new(192, 168, 1, 2),
+            subnet_mask : Ipv4Addr::new(255, 255, 255, 0),
+            routers : vec![Ipv4Addr::new(192, 168, 1, 1)],
+            dns_servers : vec![Ipv4Addr::new(8, 8, 8, 8)],
+            lease_time : 3600,
+            renewal_t1 : 1800,
+            rebinding_t2 : 3150,
+            captive_url : None,
+        };
+        let bytes = reply.serialize();
+        assert!(bytes.len() >= 300);
+
+        let parsed = DhcpPacket::parse(&bytes).expect("reply is a valid frame");
+        assert_eq!(parsed.xid, 0x0a0b_0c0d);
+        assert_eq!(parsed.message_type, Some(DHCPOFFER));
+        assert_eq!(parsed.server_id, Some(Ipv4Addr::new(192, 168, 1, 2)));
+    }
+}